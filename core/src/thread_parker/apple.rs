@@ -0,0 +1,100 @@
+// Copyright 2016 Amanieu d'Antras
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::sync::atomic::AtomicU32;
+use std::thread;
+use std::time::Duration;
+
+use libc::{c_int, c_void};
+
+use super::futex::{Futex, WaitResult};
+
+pub type ThreadParker = super::futex::ThreadParker<AppleFutex>;
+
+const UL_COMPARE_AND_WAIT: u32 = 1;
+const ULF_WAKE_ALL: u32 = 0x0000_0100;
+// Return `-errno` from the syscall instead of going through the thread's
+// `errno` slot.
+const ULF_NO_ERRNO: u32 = 0x0100_0000;
+
+extern "C" {
+    fn __ulock_wait(operation: u32, addr: *mut c_void, value: u64, timeout_us: u32) -> c_int;
+    fn __ulock_wake(operation: u32, addr: *mut c_void, wake_value: u64) -> c_int;
+}
+
+/// `Futex` backed by the private Apple `__ulock_wait`/`__ulock_wake` syscalls.
+pub struct AppleFutex;
+
+impl Futex for AppleFutex {
+    #[inline]
+    fn wait(addr: &AtomicU32, expected: u32, timeout: Option<Duration>) -> WaitResult {
+        // `__ulock_wait` takes a relative timeout in microseconds, where 0
+        // means "wait forever". A non-zero duration that rounds down to 0 is
+        // bumped to 1us so it does not accidentally block indefinitely.
+        //
+        // A duration that does not fit into `u32` microseconds is clamped and
+        // remembered as `clamped`: the wait then covers only part of the
+        // requested deadline, so an `ETIMEDOUT` at that point must be reported
+        // as a retry rather than a real timeout.
+        let mut clamped = false;
+        let timeout_us = match timeout {
+            None => 0,
+            Some(d) => {
+                let us = d.as_micros();
+                if us == 0 {
+                    1
+                } else if us > u32::MAX as u128 {
+                    clamped = true;
+                    u32::MAX
+                } else {
+                    us as u32
+                }
+            }
+        };
+        let r = unsafe {
+            __ulock_wait(
+                UL_COMPARE_AND_WAIT | ULF_NO_ERRNO,
+                addr as *const AtomicU32 as *mut c_void,
+                expected as u64,
+                timeout_us,
+            )
+        };
+        if r >= 0 {
+            WaitResult::Woken
+        } else {
+            // `ETIMEDOUT` is a real timeout only when the full requested
+            // duration elapsed; if we clamped the timeout it just means the
+            // caller should recompute the remaining deadline and wait again.
+            // Everything else — notably `EINTR` and `EFAULT` when the address
+            // races with a wake — is benign, so we re-check the state and retry.
+            match -r {
+                libc::ETIMEDOUT if !clamped => WaitResult::TimedOut,
+                _ => WaitResult::Retry,
+            }
+        }
+    }
+
+    #[inline]
+    fn wake_all(addr: *const AtomicU32) {
+        let r = unsafe {
+            __ulock_wake(
+                UL_COMPARE_AND_WAIT | ULF_WAKE_ALL | ULF_NO_ERRNO,
+                addr as *mut c_void,
+                0,
+            )
+        };
+        // `ENOENT` just means there were no waiters, which is not an error.
+        if r < 0 {
+            debug_assert_eq!(-r, libc::ENOENT);
+        }
+    }
+}
+
+#[inline]
+pub fn thread_yield() {
+    thread::yield_now();
+}
@@ -0,0 +1,132 @@
+// Copyright 2016 Amanieu d'Antras
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::marker::PhantomData;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::time::{Duration, Instant};
+
+pub const UNPARKED: u32 = 0;
+pub const PARKED: u32 = 1;
+
+/// Outcome of a single blocking wait on a futex word.
+pub enum WaitResult {
+    /// The wait returned successfully, normally because of a wake. The caller
+    /// should re-check the state and possibly return.
+    Woken,
+    /// The supplied timeout elapsed before a wake arrived.
+    TimedOut,
+    /// The wait returned spuriously (e.g. `EINTR`/`EAGAIN`) and should simply
+    /// be retried.
+    Retry,
+}
+
+/// Maps a platform's address-keyed futex syscall onto the generic parker.
+///
+/// Implementors only translate their kernel's return codes into a
+/// [`WaitResult`]; the spin loop, deadline handling and two-state protocol all
+/// live in the generic [`ThreadParker`] below.
+pub trait Futex {
+    /// Blocks while the word at `addr` equals `expected`, waking after at most
+    /// `timeout` (or indefinitely when it is `None`). It is the implementation's
+    /// responsibility to handle a timeout that overflows the kernel's
+    /// representation, either by waiting forever or by waiting for the largest
+    /// representable duration and then returning [`WaitResult::Retry`].
+    fn wait(addr: &AtomicU32, expected: u32, timeout: Option<Duration>) -> WaitResult;
+
+    /// Wakes every thread blocked on `addr`.
+    ///
+    /// Takes a raw pointer rather than a reference because the pointed-to
+    /// parker may already have been freed by the time the wake runs; it is only
+    /// ever used as a wait key, never dereferenced.
+    fn wake_all(addr: *const AtomicU32);
+}
+
+// Helper type for putting a thread to sleep until some other thread wakes it up
+pub struct ThreadParker<F> {
+    futex: AtomicU32,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Futex> super::ThreadParkerT for ThreadParker<F> {
+    type UnparkHandle = UnparkHandle<F>;
+
+    const IS_CHEAP_TO_CONSTRUCT: bool = true;
+
+    #[inline]
+    fn new() -> ThreadParker<F> {
+        ThreadParker {
+            futex: AtomicU32::new(UNPARKED),
+            _marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    unsafe fn prepare_park(&self) {
+        self.futex.store(PARKED, Relaxed);
+    }
+
+    #[inline]
+    unsafe fn timed_out(&self) -> bool {
+        self.futex.load(Relaxed) != UNPARKED
+    }
+
+    #[inline]
+    unsafe fn park(&self) {
+        while self.futex.load(Acquire) != UNPARKED {
+            match F::wait(&self.futex, PARKED, None) {
+                // Without a timeout the only way out of the wait is a wake or a
+                // spurious return; in both cases we simply re-check the state.
+                WaitResult::Woken | WaitResult::Retry | WaitResult::TimedOut => {}
+            }
+        }
+    }
+
+    #[inline]
+    unsafe fn park_until(&self, timeout: Instant) -> bool {
+        while self.futex.load(Acquire) != UNPARKED {
+            let now = Instant::now();
+            if timeout <= now {
+                return false;
+            }
+            // The timeout is passed as a relative duration and recomputed on
+            // every spurious wakeup.
+            match F::wait(&self.futex, PARKED, Some(timeout - now)) {
+                WaitResult::Woken | WaitResult::Retry => continue,
+                WaitResult::TimedOut => return false,
+            }
+        }
+        true
+    }
+
+    #[inline]
+    unsafe fn unpark_lock(&self) -> UnparkHandle<F> {
+        // We don't need to lock anything, just clear the state
+        self.futex.store(UNPARKED, Release);
+
+        UnparkHandle {
+            futex: &self.futex as *const AtomicU32,
+            _marker: PhantomData,
+        }
+    }
+}
+
+pub struct UnparkHandle<F> {
+    futex: *const AtomicU32,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Futex> super::UnparkHandleT for UnparkHandle<F> {
+    #[inline]
+    unsafe fn unpark(self) {
+        // The thread data may have been freed at this point, so we hand the raw
+        // pointer straight through without ever forming a reference to it; every
+        // `Futex` implementation only uses the address as a wait key and never
+        // inspects the pointed-to data.
+        F::wake_all(self.futex);
+    }
+}
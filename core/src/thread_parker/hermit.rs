@@ -6,97 +6,39 @@
 // copied, modified, or distributed except according to those terms.
 
 use hermit_abi::{
-    futex_wait, futex_wake, time_t, timespec, EAGAIN, EINVAL, ETIMEDOUT, FUTEX_RELATIVE_TIMEOUT,
+    futex_wait, futex_wake, time_t, timespec, EAGAIN, ETIMEDOUT, FUTEX_RELATIVE_TIMEOUT,
 };
-use std::ops::ControlFlow;
-use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::time::Instant;
-use std::{ptr, thread};
+use std::ptr;
+use std::sync::atomic::AtomicU32;
+use std::thread;
+use std::time::Duration;
 
-const UNPARKED: u32 = 0;
-const PARKED: u32 = 1;
+use super::futex::{Futex, WaitResult};
 
-// Helper type for putting a thread to sleep until some other thread wakes it up
-pub struct ThreadParker {
-    futex: AtomicU32,
-}
-
-impl super::ThreadParkerT for ThreadParker {
-    type UnparkHandle = UnparkHandle;
-
-    const IS_CHEAP_TO_CONSTRUCT: bool = true;
-
-    #[inline]
-    fn new() -> ThreadParker {
-        ThreadParker {
-            futex: AtomicU32::new(UNPARKED),
-        }
-    }
-
-    #[inline]
-    unsafe fn prepare_park(&self) {
-        self.futex.store(PARKED, Relaxed);
-    }
+pub type ThreadParker = super::futex::ThreadParker<HermitFutex>;
 
-    #[inline]
-    unsafe fn timed_out(&self) -> bool {
-        self.futex.load(Relaxed) != UNPARKED
-    }
-
-    #[inline]
-    unsafe fn park(&self) {
-        while self.futex.load(Acquire) != UNPARKED {
-            match self.futex_wait_relative(None) {
-                ControlFlow::Break(_) => return,
-                ControlFlow::Continue(()) => continue,
-            }
-        }
-    }
+/// `Futex` backed by the Hermit `futex_wait`/`futex_wake` syscalls.
+pub struct HermitFutex;
 
+impl Futex for HermitFutex {
     #[inline]
-    unsafe fn park_until(&self, timeout: Instant) -> bool {
-        while self.futex.load(Acquire) != UNPARKED {
-            let now = Instant::now();
-            if timeout <= now {
-                return false;
-            }
-            let diff = timeout - now;
-            if diff.as_secs() > time_t::MAX as u64 {
-                // Timeout overflowed, just sleep indefinitely
-                self.park();
-                return true;
+    fn wait(addr: &AtomicU32, expected: u32, timeout: Option<Duration>) -> WaitResult {
+        // Hermit only accepts relative timeouts. A duration that does not fit
+        // into a `timespec` is clamped to "wait forever".
+        let ts = timeout.and_then(|d| {
+            if d.as_secs() > time_t::MAX as u64 {
+                None
+            } else {
+                Some(timespec {
+                    tv_sec: d.as_secs() as time_t,
+                    tv_nsec: d.subsec_nanos() as i32,
+                })
             }
-            let ts = timespec {
-                tv_sec: diff.as_secs() as time_t,
-                tv_nsec: diff.subsec_nanos() as i32,
-            };
-            // ideally, we would specify an absolute timespec,
-            // but it is currently not possible to extract one from Instant
-            match self.futex_wait_relative(Some(ts)) {
-                ControlFlow::Break(x) => return x,
-                ControlFlow::Continue(()) => continue,
-            }
-        }
-        true
-    }
-
-    #[inline]
-    unsafe fn unpark_lock(&self) -> UnparkHandle {
-        // We don't need to lock anything, just clear the state
-        self.futex.store(UNPARKED, Release);
-
-        UnparkHandle { futex: self.ptr() }
-    }
-}
-
-impl ThreadParker {
-    #[inline]
-    fn futex_wait_relative(&self, ts: Option<timespec>) -> ControlFlow<bool, ()> {
+        });
         let r = unsafe {
             futex_wait(
-                self.ptr(),
-                PARKED,
+                addr as *const AtomicU32 as *mut u32,
+                expected,
                 ts.as_ref()
                     .map(|x| x as *const timespec)
                     .unwrap_or(ptr::null()),
@@ -104,18 +46,25 @@ impl ThreadParker {
             )
         };
         if r == 0 {
-            return ControlFlow::Break(true);
+            WaitResult::Woken
         } else if r == -ETIMEDOUT {
-            return ControlFlow::Break(false);
-        } else if r != -EAGAIN {
+            WaitResult::TimedOut
+        } else if r == -EAGAIN {
+            WaitResult::Retry
+        } else {
             futex_return_unexpected(r);
+            WaitResult::Retry
         }
-        ControlFlow::Continue(())
     }
 
     #[inline]
-    fn ptr(&self) -> *mut u32 {
-        &self.futex as *const AtomicU32 as *mut u32
+    fn wake_all(addr: *const AtomicU32) {
+        // `futex_wake` does not inspect the pointed data, it only uses the
+        // address as a key.
+        let r = unsafe { futex_wake(addr as *mut u32, i32::MAX) };
+        if r < 0 || r > 1 {
+            futex_return_unexpected(r);
+        }
     }
 }
 
@@ -125,22 +74,6 @@ fn futex_return_unexpected(x: i32) {
     }
 }
 
-pub struct UnparkHandle {
-    futex: *mut u32,
-}
-
-impl super::UnparkHandleT for UnparkHandle {
-    #[inline]
-    unsafe fn unpark(self) {
-        // The thread data may have been freed at this point, but the implementation of futex_wake
-        // does not actually inspect the pointed data. It only uses the address as a key.
-        let r = unsafe { futex_wake(self.futex, i32::MAX) };
-        if r < 0 || r > 1 {
-            futex_return_unexpected(r);
-        }
-    }
-}
-
 #[inline]
 pub fn thread_yield() {
     thread::yield_now();
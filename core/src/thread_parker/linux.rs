@@ -0,0 +1,87 @@
+// Copyright 2016 Amanieu d'Antras
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::sync::atomic::AtomicU32;
+use std::thread;
+use std::time::Duration;
+
+use super::futex::{Futex, WaitResult};
+
+pub type ThreadParker = super::futex::ThreadParker<LinuxFutex>;
+
+const FUTEX_WAIT: i32 = 0;
+const FUTEX_WAKE: i32 = 1;
+const FUTEX_PRIVATE: i32 = 128;
+
+/// `Futex` backed by the Linux `SYS_futex` syscall.
+pub struct LinuxFutex;
+
+impl Futex for LinuxFutex {
+    #[inline]
+    fn wait(addr: &AtomicU32, expected: u32, timeout: Option<Duration>) -> WaitResult {
+        // `FUTEX_WAIT` takes a relative timeout; a duration that does not fit
+        // into a `timespec` is clamped to "wait forever" by passing a null
+        // pointer.
+        let ts = timeout.and_then(|d| {
+            if d.as_secs() > libc::time_t::MAX as u64 {
+                None
+            } else {
+                Some(libc::timespec {
+                    tv_sec: d.as_secs() as libc::time_t,
+                    tv_nsec: d.subsec_nanos() as _,
+                })
+            }
+        });
+        let ts_ptr = ts
+            .as_ref()
+            .map(|ts| ts as *const libc::timespec)
+            .unwrap_or(std::ptr::null());
+        let r = unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                addr as *const AtomicU32,
+                FUTEX_WAIT | FUTEX_PRIVATE,
+                expected,
+                ts_ptr,
+            )
+        };
+        if r == 0 {
+            WaitResult::Woken
+        } else {
+            // `ETIMEDOUT` is a real timeout; a mismatched value (`EAGAIN`) or
+            // an interrupted wait (`EINTR`) just means we re-check and retry.
+            match errno() {
+                libc::ETIMEDOUT => WaitResult::TimedOut,
+                _ => WaitResult::Retry,
+            }
+        }
+    }
+
+    #[inline]
+    fn wake_all(addr: *const AtomicU32) {
+        // The pointed data may already be freed; the kernel only uses the
+        // address as a wait key.
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                addr,
+                FUTEX_WAKE | FUTEX_PRIVATE,
+                i32::MAX,
+            );
+        }
+    }
+}
+
+#[inline]
+fn errno() -> i32 {
+    unsafe { *libc::__errno_location() }
+}
+
+#[inline]
+pub fn thread_yield() {
+    thread::yield_now();
+}
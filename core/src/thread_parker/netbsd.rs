@@ -0,0 +1,181 @@
+// Copyright 2016 Amanieu d'Antras
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::ptr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::thread;
+use std::time::Instant;
+
+use libc::{c_int, c_long, c_void, clockid_t, lwpid_t, timespec, CLOCK_MONOTONIC};
+
+const EMPTY: usize = 0;
+const PARKED: usize = 1;
+const NOTIFIED: usize = 2;
+
+// `flags` argument to `_lwp_park`: the supplied `timespec` is an absolute
+// deadline rather than a relative timeout.
+const TIMER_ABSTIME: c_int = 1;
+
+extern "C" {
+    fn _lwp_self() -> lwpid_t;
+    fn _lwp_park(
+        clock_id: clockid_t,
+        flags: c_int,
+        ts: *const timespec,
+        unpark: lwpid_t,
+        hint: *const c_void,
+        unparkhint: *const c_void,
+    ) -> c_int;
+    fn _lwp_unpark(lwp: lwpid_t, hint: *const c_void) -> c_int;
+    fn __errno() -> *mut c_int;
+}
+
+#[inline]
+fn errno() -> c_int {
+    unsafe { *__errno() }
+}
+
+// Helper type for putting a thread to sleep until some other thread wakes it up
+pub struct ThreadParker {
+    state: AtomicUsize,
+    lwp: lwpid_t,
+}
+
+impl super::ThreadParkerT for ThreadParker {
+    type UnparkHandle = UnparkHandle;
+
+    const IS_CHEAP_TO_CONSTRUCT: bool = true;
+
+    #[inline]
+    fn new() -> ThreadParker {
+        ThreadParker {
+            state: AtomicUsize::new(EMPTY),
+            // `new` runs on the thread that will park, so this records the
+            // correct LWP to unpark later.
+            lwp: unsafe { _lwp_self() },
+        }
+    }
+
+    #[inline]
+    unsafe fn prepare_park(&self) {
+        self.state.store(PARKED, Relaxed);
+    }
+
+    #[inline]
+    unsafe fn timed_out(&self) -> bool {
+        // The unparker moves the state to `NOTIFIED`, so a still-`PARKED` state
+        // means `park_until` returned because its deadline elapsed.
+        self.state.load(Relaxed) == PARKED
+    }
+
+    #[inline]
+    unsafe fn park(&self) {
+        while self.state.load(Acquire) == PARKED {
+            let r = _lwp_park(
+                CLOCK_MONOTONIC,
+                0,
+                ptr::null(),
+                0,
+                self.hint(),
+                self.hint(),
+            );
+            // `EINTR`/`EALREADY` are benign races; loop and re-check the state.
+            if r != 0 {
+                debug_assert!(matches!(errno(), libc::EINTR | libc::EALREADY));
+            }
+        }
+    }
+
+    #[inline]
+    unsafe fn park_until(&self, timeout: Instant) -> bool {
+        // `_lwp_park` takes an *absolute* monotonic deadline. It is computed
+        // once here and reused, so unlike a relative timeout it does not drift
+        // when the wait is repeated after a spurious wakeup.
+        let ts = monotonic_deadline(timeout.saturating_duration_since(Instant::now()));
+        while self.state.load(Acquire) == PARKED {
+            if timeout <= Instant::now() {
+                return false;
+            }
+            let r = _lwp_park(
+                CLOCK_MONOTONIC,
+                TIMER_ABSTIME,
+                &ts,
+                0,
+                self.hint(),
+                self.hint(),
+            );
+            if r != 0 {
+                // A real timeout is reported on the next loop by the deadline
+                // check above; `EINTR`/`EALREADY` simply retry.
+                debug_assert!(matches!(
+                    errno(),
+                    libc::ETIMEDOUT | libc::EINTR | libc::EALREADY
+                ));
+            }
+        }
+        self.state.load(Acquire) != PARKED
+    }
+
+    #[inline]
+    unsafe fn unpark_lock(&self) -> UnparkHandle {
+        self.state.swap(NOTIFIED, Release);
+
+        UnparkHandle {
+            lwp: self.lwp,
+            hint: self.hint(),
+        }
+    }
+}
+
+impl ThreadParker {
+    #[inline]
+    fn hint(&self) -> *const c_void {
+        &self.state as *const AtomicUsize as *const c_void
+    }
+}
+
+/// Computes an absolute `CLOCK_MONOTONIC` deadline `diff` from now, saturating
+/// rather than overflowing the `timespec` fields.
+fn monotonic_deadline(diff: std::time::Duration) -> timespec {
+    let mut now = timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(CLOCK_MONOTONIC, &mut now);
+    }
+    let mut tv_sec = now.tv_sec.saturating_add(diff.as_secs() as _);
+    let mut tv_nsec = now.tv_nsec + diff.subsec_nanos() as c_long;
+    if tv_nsec >= 1_000_000_000 {
+        tv_nsec -= 1_000_000_000;
+        tv_sec = tv_sec.saturating_add(1);
+    }
+    timespec { tv_sec, tv_nsec }
+}
+
+pub struct UnparkHandle {
+    lwp: lwpid_t,
+    hint: *const c_void,
+}
+
+impl super::UnparkHandleT for UnparkHandle {
+    #[inline]
+    unsafe fn unpark(self) {
+        let r = _lwp_unpark(self.lwp, self.hint);
+        // `ESRCH` means the target already unparked (or exited) before we got
+        // here, which is not an error for us.
+        if r != 0 {
+            debug_assert_eq!(errno(), libc::ESRCH);
+        }
+    }
+}
+
+#[inline]
+pub fn thread_yield() {
+    thread::yield_now();
+}
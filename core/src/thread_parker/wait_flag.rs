@@ -0,0 +1,155 @@
+// Copyright 2016 Amanieu d'Antras
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A `ThreadParker` for targets that only expose an opaque per-thread
+//! "wait for event"/"send event to thread" primitive (e.g. SGX/Fortanix).
+//!
+//! Such a primitive is not address-keyed and may deliver a notification before
+//! the matching wait call or spuriously, so a plain `futex_wake`-style backend
+//! cannot be built on top of it. Instead we layer a small state machine on the
+//! event primitive that is robust against both lost and spurious wakeups.
+
+use std::os::fortanix_sgx::thread::current as current_thread;
+use std::os::fortanix_sgx::usercalls::raw::{send, wait, Tcs, EV_UNPARK, WAIT_INDEFINITE};
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::thread;
+use std::time::Instant;
+
+// No notification is pending and the thread is not waiting.
+const EMPTY: u32 = 0;
+// The thread has committed to blocking on its event primitive.
+const WAITING: u32 = 1;
+// A notification has been delivered.
+const NOTIFIED: u32 = 2;
+
+// Helper type for putting a thread to sleep until some other thread wakes it up
+pub struct ThreadParker {
+    state: AtomicU32,
+    tcs: Tcs,
+}
+
+impl super::ThreadParkerT for ThreadParker {
+    type UnparkHandle = UnparkHandle;
+
+    const IS_CHEAP_TO_CONSTRUCT: bool = true;
+
+    #[inline]
+    fn new() -> ThreadParker {
+        ThreadParker {
+            state: AtomicU32::new(EMPTY),
+            // `new` runs on the thread that will park, so this records the TCS
+            // the unparker has to send the event to.
+            tcs: current_thread(),
+        }
+    }
+
+    #[inline]
+    unsafe fn prepare_park(&self) {
+        self.state.store(EMPTY, Relaxed);
+    }
+
+    #[inline]
+    unsafe fn timed_out(&self) -> bool {
+        // `park_until` leaves the state `WAITING` when it returns because its
+        // deadline elapsed, and an unparker moves it to `NOTIFIED`.
+        self.state.load(Relaxed) == WAITING
+    }
+
+    #[inline]
+    unsafe fn park(&self) {
+        // If a notification arrived before we committed to waiting we are done.
+        if self.state.swap(WAITING, Acquire) == NOTIFIED {
+            self.state.store(EMPTY, Relaxed);
+            return;
+        }
+        loop {
+            wait_event(WAIT_INDEFINITE);
+            // The event mechanism may fire spuriously, so only stop once the
+            // state actually reads `NOTIFIED`.
+            if self
+                .state
+                .compare_exchange(NOTIFIED, EMPTY, Acquire, Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    #[inline]
+    unsafe fn park_until(&self, timeout: Instant) -> bool {
+        if self.state.swap(WAITING, Acquire) == NOTIFIED {
+            self.state.store(EMPTY, Relaxed);
+            return true;
+        }
+        loop {
+            let now = Instant::now();
+            if timeout <= now {
+                // Leave the state `WAITING` so `timed_out` can report the
+                // timeout to the caller.
+                return false;
+            }
+            let diff = timeout - now;
+            // Clamp a finite timeout below `WAIT_INDEFINITE` so it is never
+            // mistaken for "wait forever".
+            let timeout_ns = u64::try_from(diff.as_nanos())
+                .unwrap_or(u64::MAX)
+                .min(WAIT_INDEFINITE - 1);
+            wait_event(timeout_ns);
+            if self
+                .state
+                .compare_exchange(NOTIFIED, EMPTY, Acquire, Relaxed)
+                .is_ok()
+            {
+                return true;
+            }
+        }
+    }
+
+    #[inline]
+    unsafe fn unpark_lock(&self) -> UnparkHandle {
+        // Record whether the target had actually committed to blocking: only
+        // then does the event need to be sent, eliding the syscall otherwise.
+        let was_waiting = self.state.swap(NOTIFIED, Release) == WAITING;
+
+        UnparkHandle {
+            tcs: self.tcs,
+            was_waiting,
+        }
+    }
+}
+
+/// Blocks the current thread on the `EV_UNPARK` event, spurious wakeups
+/// included, for up to `timeout_ns` nanoseconds.
+#[inline]
+fn wait_event(timeout_ns: u64) {
+    // A failing `wait` (e.g. a timeout) is handled by the caller re-checking
+    // the parker state, so its result is deliberately ignored here.
+    let _ = unsafe { wait(EV_UNPARK, timeout_ns) };
+}
+
+pub struct UnparkHandle {
+    tcs: Tcs,
+    was_waiting: bool,
+}
+
+impl super::UnparkHandleT for UnparkHandle {
+    #[inline]
+    unsafe fn unpark(self) {
+        if self.was_waiting {
+            // `send` may spuriously wake the target or race with its wait call;
+            // the `WAITING`/`NOTIFIED` handshake makes both outcomes safe.
+            let _ = send(EV_UNPARK, Some(self.tcs));
+        }
+    }
+}
+
+#[inline]
+pub fn thread_yield() {
+    thread::yield_now();
+}